@@ -1,10 +1,22 @@
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::collections::VecDeque;
+use std::hash::Hash;
+use std::hash::Hasher;
+use std::sync::Arc;
+use std::sync::Mutex;
 use std::time::Duration;
 
+use futures::stream::{self, StreamExt};
+use jsonschema::Validator;
+use once_cell::sync::Lazy;
+use tokio::sync::mpsc;
 use tracing::{error, warn};
 
 use mcp_types::{
-    CallToolResult, CallToolResultContent, EmbeddedResourceResource, ImageContent,
-    ReadResourceResultContents,
+    AudioContent, CallToolResult, CallToolResultContent, EmbeddedResource,
+    EmbeddedResourceResource, ImageContent, ProgressNotificationParams, ProgressToken, TextContent,
+    ToolAnnotations,
 };
 
 use crate::codex::Session;
@@ -12,14 +24,272 @@ use crate::models::FunctionCallOutputPayload;
 use crate::models::ResponseInputItem;
 use crate::protocol::Event;
 use crate::protocol::EventMsg;
+use crate::protocol::McpToolCallApprovalRequestEvent;
 use crate::protocol::McpToolCallBeginEvent;
 use crate::protocol::McpToolCallEndEvent;
+use crate::protocol::McpToolCallProgressEvent;
+use crate::protocol::ReviewDecision;
 
-/// Timeout when fetching resources referenced by tool call results.
-const READ_RESOURCE_TIMEOUT: Duration = Duration::from_secs(10);
+/// Bounds how many unconsumed progress notifications a single tool call can
+/// queue up, so a server that emits them faster than we can forward them
+/// can't grow memory unboundedly; the sender just waits for room.
+const PROGRESS_CHANNEL_CAPACITY: usize = 32;
+
+/// Name prefixes that, absent explicit `annotations` from the server, we
+/// treat as a sign a tool mutates external state and therefore requires
+/// approval before running.
+const MUTATING_NAME_PREFIXES: &[&str] = &[
+    "write", "delete", "remove", "create", "update", "set", "exec", "run",
+];
+
+/// Compiled `inputSchema` validators, keyed by `(server, tool)`, so a busy
+/// tool isn't recompiled on every call.
+static SCHEMA_CACHE: Lazy<Mutex<HashMap<(String, String), Arc<Validator>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Maximum number of distinct `(server, tool, arguments)` results the result
+/// cache keeps at once.
+const MAX_RESULT_CACHE_ENTRIES: usize = 256;
+
+/// Maximum total size, in bytes, of cached results (dominated by inlined
+/// resource blobs) before the oldest entries are evicted.
+const MAX_RESULT_CACHE_BYTES: usize = 16 * 1024 * 1024;
+
+/// Opt-in cache of `CallToolResult`s for tools whose annotations mark them
+/// read-only/cacheable, keyed by a hash of `(session, server, tool,
+/// arguments)`. The session id is folded into the key (rather than the cache
+/// being partitioned per session) so two conversations never observe each
+/// other's cached results, e.g. for a filesystem or sandbox tool whose
+/// output depends on per-session state.
+static RESULT_CACHE: Lazy<Mutex<ResultCache>> = Lazy::new(|| Mutex::new(ResultCache::default()));
+
+#[derive(Default)]
+struct ResultCache {
+    entries: HashMap<u64, CallToolResult>,
+    sizes: HashMap<u64, usize>,
+    insertion_order: VecDeque<u64>,
+    total_bytes: usize,
+}
+
+impl ResultCache {
+    fn get(&self, key: u64) -> Option<CallToolResult> {
+        self.entries.get(&key).cloned()
+    }
+
+    fn insert(&mut self, key: u64, result: CallToolResult) {
+        let size_bytes = serde_json::to_vec(&result)
+            .map(|bytes| bytes.len())
+            .unwrap_or(0);
+
+        // Re-inserting an already-cached key (e.g. two concurrent calls
+        // racing on the same (session, server, tool, args)) must replace its
+        // old size rather than add to it, and must not push a second copy
+        // of `key` into `insertion_order` — either would let `total_bytes`
+        // drift upward forever and could evict the just-inserted value via
+        // the stale duplicate.
+        match self.sizes.insert(key, size_bytes) {
+            Some(old_size_bytes) => {
+                self.total_bytes = self.total_bytes.saturating_sub(old_size_bytes);
+            }
+            None => self.insertion_order.push_back(key),
+        }
+        self.total_bytes += size_bytes;
+        self.entries.insert(key, result);
+
+        while self.entries.len() > MAX_RESULT_CACHE_ENTRIES
+            || self.total_bytes > MAX_RESULT_CACHE_BYTES
+        {
+            let Some(oldest) = self.insertion_order.pop_front() else {
+                break;
+            };
+            self.entries.remove(&oldest);
+            if let Some(size_bytes) = self.sizes.remove(&oldest) {
+                self.total_bytes = self.total_bytes.saturating_sub(size_bytes);
+            }
+        }
+    }
+}
+
+/// Hashes `(session_id, server, tool_name, arguments_value)` into a
+/// result-cache key. Folding in the session id keeps results from leaking
+/// across sessions even though the cache itself is a single process-wide
+/// table. `serde_json::Value` maps serialize in sorted key order, so this is
+/// stable regardless of the order keys were inserted in the original
+/// `arguments`.
+fn result_cache_key(
+    session_id: &str,
+    server: &str,
+    tool_name: &str,
+    arguments_value: &Option<serde_json::Value>,
+) -> u64 {
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    session_id.hash(&mut hasher);
+    server.hash(&mut hasher);
+    tool_name.hash(&mut hasher);
+    if let Some(value) = arguments_value {
+        serde_json::to_string(value)
+            .unwrap_or_default()
+            .hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+/// Whether a tool is safe to serve from the result cache, per its
+/// advertised `annotations` (fetched once by the caller and shared with
+/// [`tool_requires_approval`]). Only tools the server marks read-only are
+/// eligible; anything else is assumed to have side effects and is always
+/// re-invoked.
+fn tool_is_cacheable(annotations: &Option<ToolAnnotations>) -> bool {
+    annotations
+        .as_ref()
+        .and_then(|annotations| annotations.read_only_hint)
+        .unwrap_or(false)
+}
+
+/// Classifies whether `tool_name` needs user approval before it runs, given
+/// its advertised `annotations` (fetched once by the caller and shared with
+/// [`tool_is_cacheable`]). Read-only tools never need approval; destructive
+/// ones always do. When a server doesn't advertise annotations at all, or its
+/// annotations mark the tool neither read-only nor (non-)destructive, falls
+/// back to [`MUTATING_NAME_PREFIXES`] so unannotated servers still get a
+/// safety net.
+fn tool_requires_approval(annotations: &Option<ToolAnnotations>, tool_name: &str) -> bool {
+    match annotations {
+        Some(annotations) => {
+            if annotations.read_only_hint == Some(true) {
+                false
+            } else if let Some(destructive) = annotations.destructive_hint {
+                destructive
+            } else {
+                looks_mutating(tool_name)
+            }
+        }
+        None => looks_mutating(tool_name),
+    }
+}
+
+fn looks_mutating(tool_name: &str) -> bool {
+    MUTATING_NAME_PREFIXES
+        .iter()
+        .any(|prefix| tool_name.starts_with(prefix))
+}
+
+/// Maximum number of `(session_id, server, tool)` approvals remembered at
+/// once, across all sessions. Bounds the same way [`ResultCache`] does,
+/// rather than growing forever, since nothing tells us when a session ends.
+const MAX_SESSION_APPROVALS: usize = 4096;
+
+/// Tools a user has approved for the rest of a session via
+/// `ReviewDecision::ApprovedForSession`, keyed by `(session_id, server,
+/// tool)`, so we don't re-prompt on every subsequent call to the same tool.
+#[derive(Default)]
+struct SessionApprovals {
+    approved: HashSet<(String, String, String)>,
+    insertion_order: VecDeque<(String, String, String)>,
+}
+
+impl SessionApprovals {
+    fn contains(&self, key: &(String, String, String)) -> bool {
+        self.approved.contains(key)
+    }
+
+    fn insert(&mut self, key: (String, String, String)) {
+        if !self.approved.insert(key.clone()) {
+            return;
+        }
+        self.insertion_order.push_back(key);
+
+        while self.approved.len() > MAX_SESSION_APPROVALS {
+            let Some(oldest) = self.insertion_order.pop_front() else {
+                break;
+            };
+            self.approved.remove(&oldest);
+        }
+    }
+}
+
+static SESSION_APPROVALS: Lazy<Mutex<SessionApprovals>> =
+    Lazy::new(|| Mutex::new(SessionApprovals::default()));
+
+fn session_has_approved(session_id: &str, server: &str, tool_name: &str) -> bool {
+    SESSION_APPROVALS.lock().unwrap().contains(&(
+        session_id.to_string(),
+        server.to_string(),
+        tool_name.to_string(),
+    ))
+}
+
+fn mark_session_approved(session_id: &str, server: &str, tool_name: &str) {
+    SESSION_APPROVALS.lock().unwrap().insert((
+        session_id.to_string(),
+        server.to_string(),
+        tool_name.to_string(),
+    ));
+}
+
+/// One independent MCP tool call batched together with others from the same
+/// model turn.
+pub(crate) struct McpCall {
+    pub call_id: String,
+    pub server: String,
+    pub tool_name: String,
+    pub arguments: String,
+    pub timeout: Option<Duration>,
+}
+
+/// Upper bound on how many MCP tool calls we run at once when a turn batches
+/// several independent calls together. Overridable via `CODEX_MCP_CONCURRENCY`
+/// for servers that can't take the default load.
+fn max_concurrent_mcp_calls() -> usize {
+    std::env::var("CODEX_MCP_CONCURRENCY")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|&n| n > 0)
+        .unwrap_or_else(|| num_cpus::get().max(1))
+}
+
+/// Dispatches a batch of independent MCP tool calls concurrently, bounded by
+/// [`max_concurrent_mcp_calls`], while preserving the order of `calls` in the
+/// returned `Vec`. Each call still emits its own `McpToolCallBegin`/
+/// `McpToolCallEnd` events as it starts and finishes, so calls may appear to
+/// complete out of order even though the results line up with the input
+/// order. `aggregate_timeout`, if set, fixes one deadline for the whole batch,
+/// measured from when this function is called. It only ever interrupts a
+/// call's actual tool invocation (on top of that call's own `timeout`), never
+/// the schema validation or approval wait that can precede it; time spent in
+/// those steps still counts against the deadline, so a call whose approval
+/// outlasts it fails as soon as its invocation starts.
+pub(crate) async fn handle_mcp_tool_calls(
+    sess: &Session,
+    sub_id: &str,
+    calls: Vec<McpCall>,
+    aggregate_timeout: Option<Duration>,
+) -> Vec<ResponseInputItem> {
+    let deadline = aggregate_timeout.map(|timeout| tokio::time::Instant::now() + timeout);
+    let concurrency = max_concurrent_mcp_calls();
+
+    stream::iter(calls)
+        .map(|call| {
+            handle_mcp_tool_call(
+                sess,
+                sub_id,
+                call.call_id,
+                call.server,
+                call.tool_name,
+                call.arguments,
+                call.timeout,
+                deadline,
+            )
+        })
+        .buffered(concurrency)
+        .collect()
+        .await
+}
 
 /// Handles the specified tool call dispatches the appropriate
 /// `McpToolCallBegin` and `McpToolCallEnd` events to the `Session`.
+/// `aggregate_deadline`, if set, interrupts only the tool invocation itself;
+/// see [`handle_mcp_tool_calls`] for how it relates to approval.
 pub(crate) async fn handle_mcp_tool_call(
     sess: &Session,
     sub_id: &str,
@@ -28,6 +298,7 @@ pub(crate) async fn handle_mcp_tool_call(
     tool_name: String,
     arguments: String,
     timeout: Option<Duration>,
+    aggregate_deadline: Option<tokio::time::Instant>,
 ) -> ResponseInputItem {
     // Parse the `arguments` as JSON. An empty string is OK, but invalid JSON
     // is not.
@@ -57,23 +328,160 @@ pub(crate) async fn handle_mcp_tool_call(
     });
     notify_mcp_tool_call_event(sess, sub_id, tool_call_begin_event).await;
 
-    // Perform the tool call.
-    let result = sess
-        .call_tool(&server, &tool_name, arguments_value, timeout)
-        .await
-        .map_err(|e| format!("tool call error: {e}"));
+    if let Some(validator) = schema_validator_for(sess, &server, &tool_name).await {
+        let instance = arguments_value
+            .clone()
+            .unwrap_or_else(|| serde_json::Value::Object(Default::default()));
+        let errors = describe_validation_errors(&validator, &instance);
+        if !errors.is_empty() {
+            let message = format!(
+                "arguments for {tool_name} do not match its inputSchema:\n{}",
+                errors.join("\n")
+            );
+            error!("{message}");
+
+            let tool_call_end_event = EventMsg::McpToolCallEnd(McpToolCallEndEvent {
+                call_id: call_id.clone(),
+                result: Err(message.clone()),
+                cached: false,
+            });
+            notify_mcp_tool_call_event(sess, sub_id, tool_call_end_event).await;
+
+            return ResponseInputItem::FunctionCallOutput {
+                call_id,
+                output: FunctionCallOutputPayload {
+                    content: message,
+                    success: Some(false),
+                },
+            };
+        }
+    }
+
+    let annotations = sess.tool_annotations(&server, &tool_name).await;
+
+    if tool_requires_approval(&annotations, &tool_name)
+        && !session_has_approved(sess.session_id(), &server, &tool_name)
+    {
+        let approval_event =
+            EventMsg::McpToolCallApprovalRequest(McpToolCallApprovalRequestEvent {
+                call_id: call_id.clone(),
+                server: server.clone(),
+                tool: tool_name.clone(),
+                arguments: arguments_value.clone(),
+            });
+        notify_mcp_tool_call_event(sess, sub_id, approval_event).await;
+
+        let decision = sess
+            .request_tool_call_approval(sub_id, &call_id, &server, &tool_name)
+            .await;
+        match decision {
+            ReviewDecision::Approved => {}
+            ReviewDecision::ApprovedForSession => {
+                mark_session_approved(sess.session_id(), &server, &tool_name);
+            }
+            ReviewDecision::Denied | ReviewDecision::Abort => {
+                let message =
+                    format!("{tool_name} on {server} is side-effecting and was denied approval");
+                let tool_call_end_event = EventMsg::McpToolCallEnd(McpToolCallEndEvent {
+                    call_id: call_id.clone(),
+                    result: Err(message.clone()),
+                    cached: false,
+                });
+                notify_mcp_tool_call_event(sess, sub_id, tool_call_end_event).await;
+
+                return ResponseInputItem::FunctionCallOutput {
+                    call_id,
+                    output: FunctionCallOutputPayload {
+                        content: message,
+                        success: Some(false),
+                    },
+                };
+            }
+        }
+    }
+
+    let cache_key = if tool_is_cacheable(&annotations) {
+        let key = result_cache_key(sess.session_id(), &server, &tool_name, &arguments_value);
+        if let Some(cached) = RESULT_CACHE.lock().unwrap().get(key) {
+            let tool_call_end_event = EventMsg::McpToolCallEnd(McpToolCallEndEvent {
+                call_id: call_id.clone(),
+                result: Ok(cached.clone()),
+                cached: true,
+            });
+            notify_mcp_tool_call_event(sess, sub_id, tool_call_end_event).await;
+            return ResponseInputItem::McpToolCallOutput {
+                call_id,
+                result: Ok(cached),
+            };
+        }
+        Some(key)
+    } else {
+        None
+    };
+
+    // Perform the tool call, forwarding progress notifications as they
+    // arrive and cancelling the in-flight call (rather than merely
+    // abandoning it) if the turn is aborted first.
+    let progress_token = ProgressToken::String(format!("{call_id}-progress"));
+    let (progress_tx, mut progress_rx) = mpsc::channel(PROGRESS_CHANNEL_CAPACITY);
+    let cancellation_token = sess.turn_cancellation_token(sub_id);
+
+    let invocation = async {
+        let call_future = sess.call_tool_with_progress(
+            &server,
+            &tool_name,
+            arguments_value,
+            timeout,
+            progress_token,
+            progress_tx,
+            cancellation_token,
+        );
+        tokio::pin!(call_future);
+
+        loop {
+            tokio::select! {
+                res = &mut call_future => break res.map_err(|e| format!("tool call error: {e}")),
+                Some(progress) = progress_rx.recv() => {
+                    notify_mcp_tool_call_progress(sess, sub_id, &call_id, progress).await;
+                }
+            }
+        }
+    };
+
+    let result = match aggregate_deadline {
+        Some(deadline) => match tokio::time::timeout_at(deadline, invocation).await {
+            Ok(result) => result,
+            Err(_) => {
+                warn!("aggregate MCP tool call deadline exceeded for call {call_id}");
+                Err("aggregate MCP tool call deadline exceeded".to_string())
+            }
+        },
+        None => invocation.await,
+    };
+
+    // The call may have completed in the same `select!` iteration as a
+    // trailing progress update (e.g. a final 100% before the result), so
+    // drain whatever is left in the channel before reporting the end event.
+    while let Ok(progress) = progress_rx.try_recv() {
+        notify_mcp_tool_call_progress(sess, sub_id, &call_id, progress).await;
+    }
 
     let event_result = match &result {
-        Ok(res) => match inline_image_resource(sess, &server, res).await {
+        Ok(res) => match inline_embedded_resources(res) {
             Some(inlined) => Ok(inlined),
             None => Ok(res.clone()),
         },
         Err(e) => Err(e.clone()),
     };
 
+    if let (Some(key), Ok(res)) = (cache_key, &event_result) {
+        RESULT_CACHE.lock().unwrap().insert(key, res.clone());
+    }
+
     let tool_call_end_event = EventMsg::McpToolCallEnd(McpToolCallEndEvent {
         call_id: call_id.clone(),
         result: event_result,
+        cached: false,
     });
 
     notify_mcp_tool_call_event(sess, sub_id, tool_call_end_event.clone()).await;
@@ -81,53 +489,119 @@ pub(crate) async fn handle_mcp_tool_call(
     ResponseInputItem::McpToolCallOutput { call_id, result }
 }
 
-async fn inline_image_resource(
+/// Returns the compiled `inputSchema` validator for `(server, tool_name)`,
+/// fetching and compiling it on first use and caching the result for
+/// subsequent calls. Returns `None` if the tool advertises no schema or the
+/// schema fails to compile, in which case callers should skip validation
+/// rather than reject the call.
+async fn schema_validator_for(
     sess: &Session,
     server: &str,
-    result: &CallToolResult,
-) -> Option<CallToolResult> {
-    let first = result.content.first()?;
-    let CallToolResultContent::EmbeddedResource(embedded) = first else {
-        return None;
-    };
+    tool_name: &str,
+) -> Option<Arc<Validator>> {
+    let key = (server.to_string(), tool_name.to_string());
+    if let Some(validator) = SCHEMA_CACHE.lock().unwrap().get(&key) {
+        return Some(validator.clone());
+    }
 
-    let EmbeddedResourceResource::BlobResourceContents(blob) = &embedded.resource else {
-        return None;
-    };
-
-    let mime_type = blob
-        .mime_type
-        .as_deref()
-        .filter(|m| m.starts_with("image/"))?;
-
-    let read_res = match sess
-        .read_resource(server, blob.uri.clone(), Some(READ_RESOURCE_TIMEOUT))
-        .await
-    {
-        Ok(r) => r,
+    let schema = sess.tool_input_schema(server, tool_name).await?;
+    let validator = match jsonschema::validator_for(&schema) {
+        Ok(validator) => Arc::new(validator),
         Err(e) => {
-            warn!("failed to fetch image resource: {e}");
+            warn!("{server}/{tool_name} advertised an invalid inputSchema: {e}");
             return None;
         }
     };
 
-    let Some(ReadResourceResultContents::BlobResourceContents(contents)) =
-        read_res.contents.into_iter().next()
-    else {
+    SCHEMA_CACHE.lock().unwrap().insert(key, validator.clone());
+    Some(validator)
+}
+
+/// Validates `arguments` against `validator`, returning a human-readable
+/// description of every failing field rather than just the first.
+fn describe_validation_errors(validator: &Validator, arguments: &serde_json::Value) -> Vec<String> {
+    validator
+        .iter_errors(arguments)
+        .map(|e| format!("{}: {e}", e.instance_path))
+        .collect()
+}
+
+/// Replaces every `EmbeddedResource` in `result.content` with its inline
+/// content — `ImageContent` for `image/*`, `AudioContent` for `audio/*`, and
+/// inline text for text resources — while leaving non-resource content
+/// untouched and preserving order. Per MCP's `EmbeddedResourceResource`
+/// shape, the resource's bytes/text are already attached to the embedded
+/// resource itself, so this is a plain conversion with no round trip back to
+/// the server; a resource whose media type we don't know how to inline is
+/// left as-is rather than dropped. Returns `None` if `result` has no
+/// embedded resources to inline.
+fn inline_embedded_resources(result: &CallToolResult) -> Option<CallToolResult> {
+    if !result
+        .content
+        .iter()
+        .any(|content| matches!(content, CallToolResultContent::EmbeddedResource(_)))
+    {
         return None;
-    };
+    }
+
+    let content = result
+        .content
+        .iter()
+        .cloned()
+        .map(|content| match content {
+            CallToolResultContent::EmbeddedResource(embedded) => inline_one_resource(embedded),
+            other => other,
+        })
+        .collect();
 
     Some(CallToolResult {
-        content: vec![CallToolResultContent::ImageContent(ImageContent {
-            annotations: embedded.annotations.clone(),
-            data: contents.blob,
-            mime_type: contents.mime_type.unwrap_or_else(|| mime_type.to_string()),
-            r#type: "image".to_string(),
-        })],
+        content,
         is_error: result.is_error,
     })
 }
 
+/// Converts a single embedded resource into its inline content using the
+/// bytes/text already present on `embedded.resource`, falling back to the
+/// original reference for media types we don't know how to inline.
+fn inline_one_resource(embedded: EmbeddedResource) -> CallToolResultContent {
+    let annotations = embedded.annotations.clone();
+    match embedded.resource {
+        EmbeddedResourceResource::BlobResourceContents(blob) => {
+            let mime_type = blob
+                .mime_type
+                .clone()
+                .unwrap_or_else(|| "application/octet-stream".to_string());
+            if mime_type.starts_with("image/") {
+                CallToolResultContent::ImageContent(ImageContent {
+                    annotations,
+                    data: blob.blob,
+                    mime_type,
+                    r#type: "image".to_string(),
+                })
+            } else if mime_type.starts_with("audio/") {
+                CallToolResultContent::AudioContent(AudioContent {
+                    annotations,
+                    data: blob.blob,
+                    mime_type,
+                    r#type: "audio".to_string(),
+                })
+            } else {
+                CallToolResultContent::EmbeddedResource(EmbeddedResource {
+                    annotations,
+                    resource: EmbeddedResourceResource::BlobResourceContents(blob),
+                })
+            }
+        }
+        EmbeddedResourceResource::TextResourceContents(text) => {
+            CallToolResultContent::TextContent(TextContent {
+                annotations,
+                text: text.text,
+                r#type: "text".to_string(),
+            })
+        }
+    }
+}
+
 async fn notify_mcp_tool_call_event(sess: &Session, sub_id: &str, event: EventMsg) {
     sess.send_event(Event {
         id: sub_id.to_string(),
@@ -135,3 +609,211 @@ async fn notify_mcp_tool_call_event(sess: &Session, sub_id: &str, event: EventMs
     })
     .await;
 }
+
+async fn notify_mcp_tool_call_progress(
+    sess: &Session,
+    sub_id: &str,
+    call_id: &str,
+    progress: ProgressNotificationParams,
+) {
+    let progress_event = EventMsg::McpToolCallProgress(McpToolCallProgressEvent {
+        call_id: call_id.to_string(),
+        progress: progress.progress,
+        total: progress.total,
+        message: progress.message,
+    });
+    notify_mcp_tool_call_event(sess, sub_id, progress_event).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mcp_types::BlobResourceContents;
+    use mcp_types::TextResourceContents;
+
+    fn sample_result(text: &str) -> CallToolResult {
+        CallToolResult {
+            content: vec![CallToolResultContent::TextContent(TextContent {
+                annotations: None,
+                text: text.to_string(),
+                r#type: "text".to_string(),
+            })],
+            is_error: None,
+        }
+    }
+
+    #[test]
+    fn describe_validation_errors_reports_every_failing_field() {
+        let validator = jsonschema::validator_for(&serde_json::json!({
+            "type": "object",
+            "required": ["a", "b"],
+            "properties": {
+                "a": {"type": "string"},
+                "b": {"type": "number"},
+            },
+        }))
+        .unwrap();
+
+        let errors = describe_validation_errors(&validator, &serde_json::json!({"a": 1}));
+
+        assert_eq!(
+            errors.len(),
+            2,
+            "expected one error per invalid/missing field: {errors:?}"
+        );
+    }
+
+    #[test]
+    fn result_cache_key_is_stable_under_key_reordering() {
+        let a = serde_json::json!({"x": 1, "y": 2});
+        let b = serde_json::json!({"y": 2, "x": 1});
+
+        assert_eq!(
+            result_cache_key("session", "server", "tool", &Some(a)),
+            result_cache_key("session", "server", "tool", &Some(b)),
+        );
+    }
+
+    #[test]
+    fn result_cache_key_differs_by_session() {
+        assert_ne!(
+            result_cache_key("session-a", "server", "tool", &None),
+            result_cache_key("session-b", "server", "tool", &None),
+        );
+    }
+
+    #[test]
+    fn result_cache_reinsert_does_not_double_count_bytes_or_duplicate_insertion_order() {
+        let mut cache = ResultCache::default();
+        cache.insert(1, sample_result("first"));
+        let size_after_first_insert = cache.total_bytes;
+
+        cache.insert(1, sample_result("first"));
+
+        assert_eq!(
+            cache.total_bytes, size_after_first_insert,
+            "re-inserting an existing key must not inflate total_bytes"
+        );
+        assert_eq!(
+            cache
+                .insertion_order
+                .iter()
+                .filter(|&&key| key == 1)
+                .count(),
+            1,
+            "insertion_order must not contain duplicate entries for the same key"
+        );
+    }
+
+    #[test]
+    fn result_cache_evicts_oldest_entry_once_over_the_count_limit() {
+        let mut cache = ResultCache::default();
+        for key in 0..=(MAX_RESULT_CACHE_ENTRIES as u64) {
+            cache.insert(key, sample_result("x"));
+        }
+
+        assert_eq!(cache.entries.len(), MAX_RESULT_CACHE_ENTRIES);
+        assert!(cache.get(0).is_none(), "oldest entry should be evicted");
+        assert!(cache.get(MAX_RESULT_CACHE_ENTRIES as u64).is_some());
+    }
+
+    #[test]
+    fn looks_mutating_matches_known_prefixes_only() {
+        assert!(looks_mutating("write_file"));
+        assert!(looks_mutating("delete_record"));
+        assert!(!looks_mutating("read_file"));
+        assert!(!looks_mutating("list_items"));
+    }
+
+    #[test]
+    fn tool_requires_approval_prefers_explicit_annotations_over_name_heuristic() {
+        let read_only = ToolAnnotations {
+            read_only_hint: Some(true),
+            destructive_hint: Some(true),
+            ..Default::default()
+        };
+        assert!(!tool_requires_approval(
+            &Some(read_only),
+            "delete_everything"
+        ));
+
+        let destructive = ToolAnnotations {
+            read_only_hint: Some(false),
+            destructive_hint: Some(true),
+            ..Default::default()
+        };
+        assert!(tool_requires_approval(&Some(destructive), "fetch_data"));
+
+        let unspecified = ToolAnnotations {
+            read_only_hint: Some(false),
+            destructive_hint: None,
+            ..Default::default()
+        };
+        assert!(tool_requires_approval(
+            &Some(unspecified.clone()),
+            "delete_everything"
+        ));
+        assert!(!tool_requires_approval(&Some(unspecified), "fetch_data"));
+    }
+
+    #[test]
+    fn tool_requires_approval_falls_back_to_name_heuristic_without_annotations() {
+        assert!(tool_requires_approval(&None, "delete_everything"));
+        assert!(!tool_requires_approval(&None, "read_file"));
+    }
+
+    #[test]
+    fn inline_one_resource_picks_content_type_from_mime_type() {
+        let image = EmbeddedResource {
+            annotations: None,
+            resource: EmbeddedResourceResource::BlobResourceContents(BlobResourceContents {
+                uri: "resource://image".to_string(),
+                mime_type: Some("image/png".to_string()),
+                blob: "base64data".to_string(),
+            }),
+        };
+        assert!(matches!(
+            inline_one_resource(image),
+            CallToolResultContent::ImageContent(_)
+        ));
+
+        let audio = EmbeddedResource {
+            annotations: None,
+            resource: EmbeddedResourceResource::BlobResourceContents(BlobResourceContents {
+                uri: "resource://audio".to_string(),
+                mime_type: Some("audio/wav".to_string()),
+                blob: "base64data".to_string(),
+            }),
+        };
+        assert!(matches!(
+            inline_one_resource(audio),
+            CallToolResultContent::AudioContent(_)
+        ));
+
+        let text = EmbeddedResource {
+            annotations: None,
+            resource: EmbeddedResourceResource::TextResourceContents(TextResourceContents {
+                uri: "resource://text".to_string(),
+                mime_type: Some("text/plain".to_string()),
+                text: "hello".to_string(),
+            }),
+        };
+        assert!(matches!(
+            inline_one_resource(text),
+            CallToolResultContent::TextContent(_)
+        ));
+
+        let unknown_mime_type = EmbeddedResource {
+            annotations: None,
+            resource: EmbeddedResourceResource::BlobResourceContents(BlobResourceContents {
+                uri: "resource://binary".to_string(),
+                mime_type: Some("application/pdf".to_string()),
+                blob: "base64data".to_string(),
+            }),
+        };
+        assert!(matches!(
+            inline_one_resource(unknown_mime_type),
+            CallToolResultContent::EmbeddedResource(_)
+        ));
+    }
+}